@@ -15,7 +15,8 @@ use calcard::{
     common::PartialDateTime,
     icalendar::{
         ICalendar, ICalendarComponent, ICalendarComponentType, ICalendarMethod,
-        ICalendarParticipationStatus, ICalendarProperty, ICalendarStatus, ICalendarValue,
+        ICalendarParameter, ICalendarParticipationStatus, ICalendarProperty,
+        ICalendarRecurrenceIdRange, ICalendarStatus, ICalendarValue,
     },
 };
 use std::fmt::Display;
@@ -23,6 +24,60 @@ use std::fmt::Display;
 pub fn itip_cancel(
     ical: &ICalendar,
     account_emails: &[String],
+) -> Result<ItipMessage<ICalendar>, ItipError> {
+    itip_cancel_instance(ical, account_emails, None, false)
+}
+
+// Cancels a single occurrence (or the tail of a series, with
+// `range_this_and_future`) of a recurring event. For a single occurrence it
+// also stamps an EXDATE onto the master VEVENT so it stops expanding that
+// occurrence; the tail-of-series case adds no EXDATE. This is the entry
+// point callers should use for per-instance cancellation; it requires
+// mutable access to `ical` because of that EXDATE write. `itip_cancel_instance`
+// only builds the outgoing iTIP message and leaves `ical` untouched, which is
+// why whole-series cancellation (via `itip_cancel`) goes through it directly
+// instead.
+pub fn itip_cancel_occurrence(
+    ical: &mut ICalendar,
+    account_emails: &[String],
+    instance: &InstanceId,
+    range_this_and_future: bool,
+) -> Result<ItipMessage<ICalendar>, ItipError> {
+    // "This and future" truncates the master's RRULE (typically by adding an
+    // UNTIL) rather than excluding a single date, so it is left to the
+    // caller that owns the recurrence-rule rewrite; only the single-instance
+    // case is handled here.
+    if !range_this_and_future {
+        add_instance_exdate(ical, instance);
+    }
+    itip_cancel_instance(ical, account_emails, Some(instance), range_this_and_future)
+}
+
+fn add_instance_exdate(ical: &mut ICalendar, instance: &InstanceId) {
+    if let InstanceId::Recurrence(recurrence_id) = instance {
+        if let Some(master) = ical.components.iter_mut().find(|comp| {
+            comp.component_type != ICalendarComponentType::VCalendar
+                && comp.property(&ICalendarProperty::RecurrenceId).is_none()
+        }) {
+            master.add_property(
+                ICalendarProperty::Exdate,
+                ICalendarValue::PartialDateTime(Box::new(recurrence_id.clone())),
+            );
+        }
+    }
+}
+
+// Builds the CANCEL (or decline REPLY) message for a single occurrence (or
+// the tail of a series, with `range_this_and_future`) of a recurring event
+// when `instance` is set, falling back to whole-event cancellation when it
+// is `None`. This function only inspects `ical`; callers targeting a single
+// instance should go through `itip_cancel_occurrence` instead, which also
+// stamps the EXDATE onto the master VEVENT.
+pub fn itip_cancel_instance(
+    ical: &ICalendar,
+    account_emails: &[String],
+    instance: Option<&InstanceId>,
+    range_this_and_future: bool,
 ) -> Result<ItipMessage<ICalendar>, ItipError> {
     // Prepare iTIP message
     let itip = itip_snapshot(ical, account_emails, false)?;
@@ -37,12 +92,27 @@ pub fn itip_cancel(
         comp.component_ids.push(1);
         message.components.push(comp);
 
-        // Fetch guest emails
+        // Fetch guest emails from the targeted instance(s) only
         let mut recipients = AHashSet::new();
         let mut cancel_guests = AHashSet::new();
         let mut component_type = &ICalendarComponentType::VEvent;
         let mut sequence = 0;
+        // Whole-series cancellation sources SEQUENCE from the master; a
+        // targeted occurrence (or range) sources it from that occurrence
+        // itself, since `Main` is never visited once `instance` is set.
+        let sequence_source = instance.unwrap_or(&InstanceId::Main);
         for (instance_id, comp) in &itip.components {
+            if let Some(target) = instance {
+                let in_range = if range_this_and_future {
+                    instance_id.starts_on_or_after(target)
+                } else {
+                    instance_id == target
+                };
+                if !in_range {
+                    continue;
+                }
+            }
+
             component_type = &comp.comp.component_type;
             for attendee in &comp.attendees {
                 if attendee.send_update_messages() {
@@ -52,7 +122,7 @@ pub fn itip_cancel(
             }
 
             // Increment sequence if needed
-            if instance_id == &InstanceId::Main {
+            if instance_id == sequence_source {
                 sequence = comp.sequence.unwrap_or_default() + 1;
             }
         }
@@ -64,13 +134,16 @@ pub fn itip_cancel(
                 sequence,
                 dt_stamp,
                 cancel_guests.iter(),
+                instance,
+                range_this_and_future,
             ));
 
             Ok(ItipMessage {
                 to: recipients.into_iter().collect(),
-                summary: ItipSummary::Cancel(
-                    itip.main_instance_or_default().build_summary(None, &[]),
-                ),
+                summary: ItipSummary::Cancel {
+                    instance: instance.cloned().unwrap_or(InstanceId::Main),
+                    summary: itip.main_instance_or_default().build_summary(None, &[]),
+                },
                 from: itip.organizer.email.email,
                 from_organizer: true,
                 message,
@@ -84,10 +157,22 @@ pub fn itip_cancel(
             .components
             .push(itip_build_envelope(ICalendarMethod::Reply));
 
-        // Decline attendance for all instances that have local attendees
+        // Decline attendance for the targeted instance(s) that have local
+        // attendees, or for all instances when cancelling the whole series.
         let mut mail_from = None;
         let mut email_rcpt = AHashSet::new();
         for (instance_id, comp) in &itip.components {
+            if let Some(target) = instance {
+                let in_range = if range_this_and_future {
+                    instance_id.starts_on_or_after(target)
+                } else {
+                    instance_id == target
+                };
+                if !in_range {
+                    continue;
+                }
+            }
+
             if let Some((cancel_comp, attendee_email)) =
                 attendee_decline(instance_id, &itip, comp, &dt_stamp, &mut email_rcpt)
             {
@@ -127,6 +212,8 @@ pub(crate) fn build_cancel_component<T, I>(
     sequence: i64,
     dt_stamp: PartialDateTime,
     cancel_guests: T,
+    instance: Option<&InstanceId>,
+    range_this_and_future: bool,
 ) -> ICalendarComponent
 where
     T: Iterator<Item = I>,
@@ -134,7 +221,7 @@ where
 {
     let mut cancel_comp = ICalendarComponent {
         component_type,
-        entries: Vec::with_capacity(7),
+        entries: Vec::with_capacity(8),
         component_ids: vec![],
     };
     cancel_comp.add_property(
@@ -149,6 +236,21 @@ where
         ICalendarValue::Text(itip.organizer.email.to_string()),
     );
 
+    // Cancelling a single occurrence (or the tail of a series) needs a
+    // RECURRENCE-ID pinning which instance this CANCEL applies to, so the
+    // attendee's client leaves the rest of the series untouched.
+    if let Some(InstanceId::Recurrence(recurrence_id)) = instance {
+        cancel_comp.add_property_with_parameters(
+            ICalendarProperty::RecurrenceId,
+            ICalendarValue::PartialDateTime(Box::new(recurrence_id.clone())),
+            if range_this_and_future {
+                vec![ICalendarParameter::range(ICalendarRecurrenceIdRange::ThisAndFuture)]
+            } else {
+                vec![]
+            },
+        );
+    }
+
     for email in cancel_guests {
         cancel_comp.add_property(
             ICalendarProperty::Attendee,
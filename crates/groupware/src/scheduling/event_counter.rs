@@ -0,0 +1,360 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::scheduling::{
+    ItipError, ItipMessage, ItipSnapshots, ItipSummary,
+    itip::itip_build_envelope,
+    snapshot::itip_snapshot,
+};
+use calcard::{
+    common::PartialDateTime,
+    icalendar::{ICalendar, ICalendarComponent, ICalendarMethod, ICalendarProperty, ICalendarValue},
+};
+
+// RFC 5546 section 3.2.7: an attendee proposes alternate dates/times for the
+// event without being able to change anything else about it.
+pub fn itip_counter(
+    ical: &ICalendar,
+    account_emails: &[String],
+) -> Result<ItipMessage<ICalendar>, ItipError> {
+    let itip = itip_snapshot(ical, account_emails, false)?;
+
+    if itip.organizer.email.is_local {
+        return Err(ItipError::NotOrganizerNorAttendee);
+    }
+
+    let main = itip.main_instance_or_default();
+    let attendee = main
+        .attendees
+        .iter()
+        .find(|attendee| attendee.email.is_local)
+        .ok_or(ItipError::NotOrganizerNorAttendee)?;
+
+    let dt_stamp = PartialDateTime::now();
+    let mut message = ICalendar {
+        components: Vec::with_capacity(2),
+    };
+    let mut envelope = itip_build_envelope(ICalendarMethod::Counter);
+    envelope.component_ids.push(1);
+    message.components.push(envelope);
+
+    // The counter proposal is a copy of the current component: it keeps the
+    // original UID and SEQUENCE, and only the DTSTART/DTEND (and an optional
+    // COMMENT) the attendee changed differ from the organizer's copy.
+    let mut comp = main.comp.clone();
+    refresh_dtstamp(&mut comp, dt_stamp);
+    message.components.push(comp);
+
+    Ok(ItipMessage {
+        to: vec![itip.organizer.email.email.clone()],
+        from: attendee.email.email.clone(),
+        from_organizer: false,
+        summary: ItipSummary::Counter(
+            main.build_summary(None, &[ICalendarProperty::Dtstart, ICalendarProperty::Dtend]),
+        ),
+        message,
+    })
+}
+
+// Organizer-side response to an incoming COUNTER: either accept the proposed
+// times (re-issuing a REQUEST with a bumped SEQUENCE) or reject it with a
+// DECLINECOUNTER reply addressed back to the proposing attendee. `proposer_email`
+// must be the address that actually sent the COUNTER (e.g. the envelope
+// sender of the inbound iMIP message) — the COUNTER component itself carries
+// the full, unmodified ATTENDEE list, so it cannot be inferred by locality
+// alone once an event has more than one non-local attendee.
+pub fn organizer_handle_counter(
+    ical: &ICalendar,
+    counter_ical: &ICalendar,
+    account_emails: &[String],
+    proposer_email: &str,
+    accept: bool,
+) -> Result<ItipMessage<ICalendar>, ItipError> {
+    let itip = itip_snapshot(ical, account_emails, false)?;
+    if !itip.organizer.email.is_local {
+        return Err(ItipError::NotOrganizer);
+    }
+
+    let counter_itip = itip_snapshot(counter_ical, account_emails, false)?;
+    let counter_main = counter_itip.main_instance_or_default();
+    let attendee_emails: Vec<&str> = counter_main
+        .attendees
+        .iter()
+        .map(|attendee| attendee.email.email.as_str())
+        .collect();
+    find_proposer(&attendee_emails, proposer_email).ok_or(ItipError::NothingToSend)?;
+
+    let dt_stamp = PartialDateTime::now();
+    let main = itip.main_instance_or_default();
+    let mut message = ICalendar {
+        components: Vec::with_capacity(2),
+    };
+    let mut envelope = itip_build_envelope(counter_response_method(accept));
+    envelope.component_ids.push(1);
+    message.components.push(envelope);
+
+    if accept {
+        // `counter_main.comp` is already a populated, previously-sent
+        // component (it already carries a DTSTAMP/SEQUENCE), so the clone's
+        // existing values must be replaced rather than appended to.
+        let mut comp = counter_main.comp.clone();
+        refresh_dtstamp(&mut comp, dt_stamp);
+        refresh_sequence(&mut comp, bumped_sequence(main.sequence));
+        message.components.push(comp);
+
+        Ok(ItipMessage {
+            to: main
+                .attendees
+                .iter()
+                .filter(|attendee| attendee.send_update_messages())
+                .map(|attendee| attendee.email.email.clone())
+                .collect(),
+            from: itip.organizer.email.email.clone(),
+            from_organizer: true,
+            summary: ItipSummary::Rescheduled(
+                main.build_summary(None, &[ICalendarProperty::Dtstart, ICalendarProperty::Dtend]),
+            ),
+            message,
+        })
+    } else {
+        message
+            .components
+            .push(build_declinecounter_component(&itip, dt_stamp));
+
+        Ok(ItipMessage {
+            to: vec![proposer_email.to_string()],
+            from: itip.organizer.email.email.clone(),
+            from_organizer: true,
+            summary: ItipSummary::DeclineCounter(main.build_summary(None, &[])),
+            message,
+        })
+    }
+}
+
+// Whichever attendee email actually sent the inbound COUNTER — kept separate
+// from `ItipSnapshots` so it can be unit tested without a calendar fixture.
+fn find_proposer<'a>(attendee_emails: &[&'a str], proposer_email: &str) -> Option<&'a str> {
+    attendee_emails
+        .iter()
+        .copied()
+        .find(|email| *email == proposer_email)
+}
+
+// Accepting a COUNTER re-issues the invitation (REQUEST); rejecting it
+// replies with a DECLINECOUNTER. Pulled out so the branching itself, not
+// just its side effects, has a direct test.
+fn counter_response_method(accept: bool) -> ICalendarMethod {
+    if accept {
+        ICalendarMethod::Request
+    } else {
+        ICalendarMethod::DeclineCounter
+    }
+}
+
+// RFC 5546 requires SEQUENCE to be incremented whenever a REQUEST carries a
+// materially changed start/end time, as accepting a COUNTER does.
+fn bumped_sequence(current: Option<i64>) -> i64 {
+    current.unwrap_or_default() + 1
+}
+
+// `add_dtstamp`/`add_sequence` are append-only, so calling them on a clone
+// of an already-populated component (as opposed to a freshly constructed
+// one) would leave two DTSTAMP/SEQUENCE properties on the same VEVENT,
+// which is invalid per RFC 5545. Drop the existing value first.
+fn refresh_dtstamp(comp: &mut ICalendarComponent, dt_stamp: PartialDateTime) {
+    comp.remove_property(&ICalendarProperty::Dtstamp);
+    comp.add_dtstamp(dt_stamp);
+}
+
+fn refresh_sequence(comp: &mut ICalendarComponent, sequence: i64) {
+    comp.remove_property(&ICalendarProperty::Sequence);
+    comp.add_sequence(sequence);
+}
+
+fn build_declinecounter_component(
+    itip: &ItipSnapshots<'_>,
+    dt_stamp: PartialDateTime,
+) -> ICalendarComponent {
+    let main = itip.main_instance_or_default();
+    let mut comp = ICalendarComponent {
+        component_type: main.comp.component_type.clone(),
+        entries: Vec::with_capacity(4),
+        component_ids: vec![],
+    };
+    comp.add_dtstamp(dt_stamp);
+    comp.add_uid(itip.uid);
+    comp.add_sequence(main.sequence.unwrap_or_default());
+    comp.add_property(
+        ICalendarProperty::Organizer,
+        ICalendarValue::Text(itip.organizer.email.to_string()),
+    );
+    comp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bumped_sequence, counter_response_method, find_proposer, itip_counter,
+        organizer_handle_counter,
+    };
+    use crate::scheduling::itip::itip_method;
+    use calcard::icalendar::{
+        ICalendar, ICalendarComponent, ICalendarComponentType, ICalendarMethod, ICalendarProperty,
+        ICalendarValue,
+    };
+
+    fn fixture_event(organizer: &str, attendees: &[&str], sequence: i64) -> ICalendar {
+        let mut comp = ICalendarComponent {
+            component_type: ICalendarComponentType::VEvent,
+            entries: Vec::with_capacity(4 + attendees.len()),
+            component_ids: vec![],
+        };
+        comp.add_uid("event-1");
+        comp.add_sequence(sequence);
+        comp.add_property(
+            ICalendarProperty::Organizer,
+            ICalendarValue::Text(format!("mailto:{organizer}")),
+        );
+        for attendee in attendees {
+            comp.add_property(
+                ICalendarProperty::Attendee,
+                ICalendarValue::Text(format!("mailto:{attendee}")),
+            );
+        }
+        comp.add_property(
+            ICalendarProperty::Summary,
+            ICalendarValue::Text("Team sync".to_string()),
+        );
+        ICalendar {
+            components: vec![comp],
+        }
+    }
+
+    fn sequence_of(message: &ICalendar) -> i64 {
+        message
+            .components
+            .iter()
+            .find(|comp| comp.component_type != ICalendarComponentType::VCalendar)
+            .and_then(|comp| comp.property(&ICalendarProperty::Sequence))
+            .and_then(|value| match value {
+                ICalendarValue::Integer(n) => Some(*n),
+                _ => None,
+            })
+            .expect("produced component has a SEQUENCE")
+    }
+
+    #[test]
+    fn itip_counter_is_addressed_to_the_organizer() {
+        let ical = fixture_event("organizer@example.com", &["attendee@example.com"], 0);
+        let account_emails = vec!["attendee@example.com".to_string()];
+
+        let result = itip_counter(&ical, &account_emails).unwrap();
+
+        assert_eq!(result.to, vec!["organizer@example.com".to_string()]);
+        assert_eq!(result.from, "attendee@example.com");
+        assert!(!result.from_organizer);
+        assert_eq!(itip_method(&result.message), ICalendarMethod::Counter);
+    }
+
+    #[test]
+    fn accepting_a_counter_reissues_a_request_with_a_bumped_sequence() {
+        let ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let counter_ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let account_emails = vec!["organizer@example.com".to_string()];
+
+        let result = organizer_handle_counter(
+            &ical,
+            &counter_ical,
+            &account_emails,
+            "attendee@example.com",
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(result.to, vec!["attendee@example.com".to_string()]);
+        assert!(result.from_organizer);
+        assert_eq!(itip_method(&result.message), ICalendarMethod::Request);
+        assert_eq!(sequence_of(&result.message), 4);
+    }
+
+    #[test]
+    fn rejecting_a_counter_sends_a_declinecounter_to_the_proposer() {
+        let ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let counter_ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let account_emails = vec!["organizer@example.com".to_string()];
+
+        let result = organizer_handle_counter(
+            &ical,
+            &counter_ical,
+            &account_emails,
+            "attendee@example.com",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(result.to, vec!["attendee@example.com".to_string()]);
+        assert!(result.from_organizer);
+        assert_eq!(
+            itip_method(&result.message),
+            ICalendarMethod::DeclineCounter
+        );
+    }
+
+    #[test]
+    fn rejecting_a_counter_from_an_unknown_proposer_fails() {
+        let ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let counter_ical = fixture_event("organizer@example.com", &["attendee@example.com"], 3);
+        let account_emails = vec!["organizer@example.com".to_string()];
+
+        let result = organizer_handle_counter(
+            &ical,
+            &counter_ical,
+            &account_emails,
+            "stranger@example.com",
+            false,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn accepting_reissues_a_request() {
+        assert_eq!(counter_response_method(true), ICalendarMethod::Request);
+    }
+
+    #[test]
+    fn rejecting_sends_a_declinecounter() {
+        assert_eq!(
+            counter_response_method(false),
+            ICalendarMethod::DeclineCounter
+        );
+    }
+
+    #[test]
+    fn bumps_a_missing_sequence_to_one() {
+        assert_eq!(bumped_sequence(None), 1);
+    }
+
+    #[test]
+    fn bumps_an_existing_sequence_by_one() {
+        assert_eq!(bumped_sequence(Some(4)), 5);
+    }
+
+    #[test]
+    fn finds_the_proposer_among_the_attendees() {
+        let attendees = ["alice@example.com", "bob@example.com"];
+        assert_eq!(
+            find_proposer(&attendees, "bob@example.com"),
+            Some("bob@example.com")
+        );
+    }
+
+    #[test]
+    fn does_not_find_an_attendee_who_never_proposed() {
+        let attendees = ["alice@example.com", "bob@example.com"];
+        assert_eq!(find_proposer(&attendees, "carol@example.com"), None);
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::scheduling::{
+    ItipError, ItipMessage, ItipSummary, itip::itip_build_envelope, snapshot::itip_snapshot,
+};
+use calcard::{
+    common::PartialDateTime,
+    icalendar::{
+        ICalendar, ICalendarComponent, ICalendarMethod, ICalendarParameter,
+        ICalendarParticipationStatus, ICalendarProperty, ICalendarValue,
+    },
+};
+
+// RFC 6638 section 3.2.5: a local attendee hands their invitation off to a
+// delegate. Produces two messages: a REQUEST forwarding the invitation to
+// the delegate, and a REPLY telling the organizer the delegator's own
+// PARTSTAT is now DELEGATED.
+pub fn itip_delegate(
+    ical: &ICalendar,
+    account_emails: &[String],
+    delegate_email: &str,
+) -> Result<Vec<ItipMessage<ICalendar>>, ItipError> {
+    let itip = itip_snapshot(ical, account_emails, false)?;
+
+    if itip.organizer.email.is_local {
+        return Err(ItipError::NotOrganizerNorAttendee);
+    }
+
+    let main = itip.main_instance_or_default();
+    let delegator = main
+        .attendees
+        .iter()
+        .find(|attendee| attendee.email.is_local)
+        .ok_or(ItipError::NotOrganizerNorAttendee)?;
+    let delegator_email = delegator.email.email.clone();
+    let dt_stamp = PartialDateTime::now();
+
+    // Forward the invitation to the delegate: same component, with the
+    // delegator's line marked DELEGATED and a new ATTENDEE line added for
+    // the delegate.
+    let mut to_delegate = ICalendar {
+        components: Vec::with_capacity(2),
+    };
+    let mut envelope = itip_build_envelope(ICalendarMethod::Request);
+    envelope.component_ids.push(1);
+    to_delegate.components.push(envelope);
+
+    // `main.comp` is already a populated, previously-sent component (it
+    // already carries a DTSTAMP), so the clone's existing value must be
+    // replaced rather than appended to, or the forwarded VEVENT would end
+    // up with two DTSTAMP properties (invalid per RFC 5545).
+    let mut comp = main.comp.clone();
+    comp.remove_property(&ICalendarProperty::Dtstamp);
+    comp.add_dtstamp(dt_stamp.clone());
+    set_attendee_delegation(
+        &mut comp,
+        &delegator_email,
+        ICalendarParticipationStatus::Delegated,
+        Some(delegate_email),
+    );
+    comp.add_property_with_parameters(
+        ICalendarProperty::Attendee,
+        ICalendarValue::Text(delegate_email.to_string()),
+        vec![ICalendarParameter::delegated_from(&delegator_email)],
+    );
+    to_delegate.components.push(comp);
+
+    // Tell the organizer the delegator's own PARTSTAT is now DELEGATED.
+    let mut to_organizer = ICalendar {
+        components: Vec::with_capacity(2),
+    };
+    let mut envelope = itip_build_envelope(ICalendarMethod::Reply);
+    envelope.component_ids.push(1);
+    to_organizer.components.push(envelope);
+
+    let mut reply_comp = ICalendarComponent {
+        component_type: main.comp.component_type.clone(),
+        entries: Vec::with_capacity(6),
+        component_ids: vec![],
+    };
+    reply_comp.add_dtstamp(dt_stamp);
+    reply_comp.add_uid(itip.uid);
+    reply_comp.add_sequence(main.sequence.unwrap_or_default());
+    reply_comp.add_property(
+        ICalendarProperty::Organizer,
+        ICalendarValue::Text(itip.organizer.email.to_string()),
+    );
+    reply_comp.add_property_with_parameters(
+        ICalendarProperty::Attendee,
+        ICalendarValue::Text(delegator_email.clone()),
+        vec![
+            ICalendarParameter::partstat(ICalendarParticipationStatus::Delegated),
+            ICalendarParameter::delegated_to(delegate_email),
+        ],
+    );
+    to_organizer.components.push(reply_comp);
+
+    Ok(vec![
+        ItipMessage {
+            to: vec![delegate_email.to_string()],
+            from: delegator_email.clone(),
+            from_organizer: false,
+            summary: ItipSummary::Delegated {
+                current: main.build_summary(None, &[]),
+                delegate: delegate_email.to_string(),
+            },
+            message: to_delegate,
+        },
+        ItipMessage {
+            to: vec![itip.organizer.email.email.clone()],
+            from: delegator_email,
+            from_organizer: false,
+            summary: ItipSummary::Delegated {
+                current: main.build_summary(None, &[]),
+                delegate: delegate_email.to_string(),
+            },
+            message: to_organizer,
+        },
+    ])
+}
+
+fn set_attendee_delegation(
+    comp: &mut ICalendarComponent,
+    attendee_email: &str,
+    part_stat: ICalendarParticipationStatus,
+    delegated_to: Option<&str>,
+) {
+    if let Some(entry) = comp
+        .entries
+        .iter_mut()
+        .find(|entry| entry.is_attendee(attendee_email))
+    {
+        entry.set_param(ICalendarParameter::partstat(part_stat));
+        if let Some(delegate) = delegated_to {
+            entry.set_param(ICalendarParameter::delegated_to(delegate));
+        }
+    }
+}
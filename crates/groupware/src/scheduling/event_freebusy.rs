@@ -0,0 +1,280 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::scheduling::{
+    ItipError, ItipMessage, ItipSummary, itip::itip_build_envelope, snapshot::itip_snapshot,
+};
+use calcard::{
+    common::PartialDateTime,
+    icalendar::{
+        ICalendar, ICalendarComponent, ICalendarComponentType, ICalendarFreeBusyType,
+        ICalendarMethod, ICalendarParameter, ICalendarPeriod, ICalendarProperty, ICalendarValue,
+    },
+};
+
+// Handles an incoming METHOD:REQUEST carrying a VFREEBUSY query: replies with
+// the local attendee's busy intervals inside the requested DTSTART/DTEND
+// window. The store lookup that produces `busy_periods` stays outside this
+// module, so it is passed in as an iterator of raw (start, end, status)
+// tuples rather than fetched here.
+pub fn itip_freebusy(
+    ical: &ICalendar,
+    account_emails: &[String],
+    busy_periods: impl IntoIterator<Item = (PartialDateTime, PartialDateTime, ICalendarFreeBusyType)>,
+) -> Result<ItipMessage<ICalendar>, ItipError> {
+    let itip = itip_snapshot(ical, account_emails, false)?;
+    let main = itip.main_instance_or_default();
+
+    if main.comp.component_type != ICalendarComponentType::VFreebusy {
+        return Err(ItipError::NothingToSend);
+    }
+
+    let attendee = main
+        .attendees
+        .iter()
+        .find(|attendee| attendee.email.is_local)
+        .ok_or(ItipError::NothingToSend)?;
+
+    let (window_start, window_end) = freebusy_window(main.comp).ok_or(ItipError::NothingToSend)?;
+
+    let merged = clamp_and_merge(busy_periods, window_start, window_end);
+
+    let dt_stamp = PartialDateTime::now();
+    let mut message = ICalendar {
+        components: Vec::with_capacity(2),
+    };
+    let mut envelope = itip_build_envelope(ICalendarMethod::Reply);
+    envelope.component_ids.push(1);
+    message.components.push(envelope);
+
+    // Rendered once up front, before `merged` is consumed below, so the
+    // human-readable summary actually lists the busy/tentative periods being
+    // reported rather than describing the (mostly empty) VFREEBUSY component.
+    let periods_text = if merged.is_empty() {
+        "No busy periods in the requested window.".to_string()
+    } else {
+        merged
+            .iter()
+            .map(|(start, end, status)| format!("{}: {start} - {end}", freebusy_type_str(status)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mut comp = ICalendarComponent {
+        component_type: ICalendarComponentType::VFreebusy,
+        entries: Vec::with_capacity(merged.len() + 4),
+        component_ids: vec![],
+    };
+    comp.add_dtstamp(dt_stamp);
+    comp.add_uid(itip.uid);
+    comp.add_property(
+        ICalendarProperty::Organizer,
+        ICalendarValue::Text(itip.organizer.email.to_string()),
+    );
+    comp.add_property(
+        ICalendarProperty::Attendee,
+        ICalendarValue::Text(attendee.email.to_string()),
+    );
+    for (start, end, status) in merged {
+        comp.add_property_with_parameters(
+            ICalendarProperty::Freebusy,
+            ICalendarValue::Period(ICalendarPeriod { start, end }),
+            vec![ICalendarParameter::fbtype(status)],
+        );
+    }
+    message.components.push(comp);
+
+    Ok(ItipMessage {
+        to: vec![itip.organizer.email.email.clone()],
+        from: attendee.email.email.clone(),
+        from_organizer: false,
+        summary: ItipSummary::FreeBusy(periods_text),
+        message,
+    })
+}
+
+// Clamps every interval to `[window_start, window_end)`, drops intervals
+// that end up empty or inverted, then coalesces overlapping (or touching)
+// intervals that share the same status so the reply reports the minimal
+// set of periods. Kept generic over `T`/`S` so the clamp/merge arithmetic
+// can be unit tested without needing a full `PartialDateTime`/
+// `ICalendarFreeBusyType` fixture.
+fn clamp_and_merge<T, S>(
+    periods: impl IntoIterator<Item = (T, T, S)>,
+    window_start: T,
+    window_end: T,
+) -> Vec<(T, T, S)>
+where
+    T: Ord + Clone,
+    S: PartialEq,
+{
+    let mut periods: Vec<_> = periods
+        .into_iter()
+        .filter_map(|(start, end, status)| {
+            let start = start.max(window_start.clone());
+            let end = end.min(window_end.clone());
+            (start < end).then_some((start, end, status))
+        })
+        .collect();
+    periods.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut merged: Vec<(T, T, S)> = Vec::with_capacity(periods.len());
+    for (start, end, status) in periods {
+        if let Some(last) = merged
+            .iter_mut()
+            .rev()
+            .find(|last| last.2 == status)
+            .filter(|last| start <= last.1)
+        {
+            if end > last.1 {
+                last.1 = end;
+            }
+        } else {
+            merged.push((start, end, status));
+        }
+    }
+    merged
+}
+
+fn freebusy_type_str(status: &ICalendarFreeBusyType) -> &'static str {
+    match status {
+        ICalendarFreeBusyType::Busy => "Busy",
+        ICalendarFreeBusyType::BusyTentative => "Busy (tentative)",
+        ICalendarFreeBusyType::BusyUnavailable => "Unavailable",
+        ICalendarFreeBusyType::Free => "Free",
+    }
+}
+
+fn freebusy_window(comp: &ICalendarComponent) -> Option<(PartialDateTime, PartialDateTime)> {
+    let start = comp
+        .property(&ICalendarProperty::Dtstart)?
+        .as_partial_date_time()?
+        .clone();
+    let end = comp
+        .property(&ICalendarProperty::Dtend)?
+        .as_partial_date_time()?
+        .clone();
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clamp_and_merge, itip_freebusy};
+    use crate::scheduling::{ItipSummary, itip::itip_method};
+    use calcard::{
+        common::PartialDateTime,
+        icalendar::{
+            ICalendar, ICalendarComponent, ICalendarComponentType, ICalendarFreeBusyType,
+            ICalendarMethod, ICalendarProperty, ICalendarValue,
+        },
+    };
+
+    fn dt(hour: u8) -> PartialDateTime {
+        PartialDateTime {
+            year: Some(2026),
+            month: Some(1),
+            day: Some(1),
+            hour: Some(hour),
+            minute: Some(0),
+            second: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn fixture_freebusy(
+        attendee: &str,
+        window_start: PartialDateTime,
+        window_end: PartialDateTime,
+    ) -> ICalendar {
+        let mut comp = ICalendarComponent {
+            component_type: ICalendarComponentType::VFreebusy,
+            entries: Vec::with_capacity(4),
+            component_ids: vec![],
+        };
+        comp.add_uid("freebusy-1");
+        comp.add_property(
+            ICalendarProperty::Organizer,
+            ICalendarValue::Text("mailto:organizer@example.com".to_string()),
+        );
+        comp.add_property(
+            ICalendarProperty::Attendee,
+            ICalendarValue::Text(format!("mailto:{attendee}")),
+        );
+        comp.add_property(
+            ICalendarProperty::Dtstart,
+            ICalendarValue::PartialDateTime(Box::new(window_start)),
+        );
+        comp.add_property(
+            ICalendarProperty::Dtend,
+            ICalendarValue::PartialDateTime(Box::new(window_end)),
+        );
+        ICalendar {
+            components: vec![comp],
+        }
+    }
+
+    #[test]
+    fn itip_freebusy_reports_the_requested_busy_periods() {
+        let ical = fixture_freebusy("attendee@example.com", dt(9), dt(17));
+        let busy = vec![(dt(10), dt(11), ICalendarFreeBusyType::Busy)];
+
+        let result = itip_freebusy(&ical, &["attendee@example.com".to_string()], busy).unwrap();
+
+        assert_eq!(result.from, "attendee@example.com");
+        assert_eq!(result.to, vec!["organizer@example.com".to_string()]);
+        assert!(!result.from_organizer);
+        assert_eq!(itip_method(&result.message), ICalendarMethod::Reply);
+        match &result.summary {
+            ItipSummary::FreeBusy(periods) => assert!(periods.contains("Busy")),
+            other => panic!("expected FreeBusy summary, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn itip_freebusy_clamps_periods_outside_the_window_to_empty() {
+        let ical = fixture_freebusy("attendee@example.com", dt(9), dt(17));
+        let busy = vec![(dt(20), dt(21), ICalendarFreeBusyType::Busy)];
+
+        let result = itip_freebusy(&ical, &["attendee@example.com".to_string()], busy).unwrap();
+
+        match &result.summary {
+            ItipSummary::FreeBusy(periods) => {
+                assert!(periods.contains("No busy periods"))
+            }
+            other => panic!("expected FreeBusy summary, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn clamps_periods_to_the_window() {
+        let merged = clamp_and_merge(vec![(0, 20, "busy")], 5, 10);
+        assert_eq!(merged, vec![(5, 10, "busy")]);
+    }
+
+    #[test]
+    fn drops_periods_outside_the_window() {
+        let merged = clamp_and_merge(vec![(20, 30, "busy")], 0, 10);
+        assert!(merged.is_empty());
+    }
+
+    #[test]
+    fn merges_overlapping_periods_of_the_same_status() {
+        let merged = clamp_and_merge(vec![(0, 5, "busy"), (4, 8, "busy")], 0, 10);
+        assert_eq!(merged, vec![(0, 8, "busy")]);
+    }
+
+    #[test]
+    fn merges_touching_periods_of_the_same_status() {
+        let merged = clamp_and_merge(vec![(0, 5, "busy"), (5, 8, "busy")], 0, 10);
+        assert_eq!(merged, vec![(0, 8, "busy")]);
+    }
+
+    #[test]
+    fn keeps_different_statuses_separate_even_when_overlapping() {
+        let merged = clamp_and_merge(vec![(0, 5, "busy"), (4, 8, "tentative")], 0, 10);
+        assert_eq!(merged, vec![(0, 5, "busy"), (4, 8, "tentative")]);
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::scheduling::{InstanceId, ItipMessage, ItipSummary, itip::itip_method};
+use calcard::icalendar::{ICalendar, ICalendarComponentType, ICalendarParticipationStatus};
+use mail_builder::MessageBuilder;
+
+impl ItipMessage<ICalendar> {
+    // Renders this scheduling message into a standards-compliant iMIP email
+    // (RFC 6047 / RFC 2447): a `multipart/alternative` human-readable body, a
+    // `text/calendar; method=...` part carrying the calendar object, and an
+    // `application/ics` attachment copy for clients that only look at
+    // attachments rather than inline calendar parts.
+    pub fn into_mime(&self) -> Vec<u8> {
+        let method = itip_method(&self.message);
+        let component = self.component_type();
+        let ical_text = self.message.to_string();
+        let (subject, text_body, html_body) = self.render_body();
+
+        MessageBuilder::new()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(subject)
+            .text_body(text_body)
+            .html_body(html_body)
+            .inline(
+                format!(
+                    "text/calendar; method={}; component={}",
+                    method.as_str(),
+                    component.as_str()
+                ),
+                "invite",
+                ical_text.clone(),
+            )
+            .attachment("application/ics", "invite.ics", ical_text)
+            .write_to_vec()
+            .unwrap_or_default()
+    }
+
+    // The envelope component (built by `itip_build_envelope`) only carries
+    // the METHOD; the actual scheduling object, and its component type, is
+    // whichever non-VCALENDAR component it links to (VEVENT, VFREEBUSY, ...).
+    fn component_type(&self) -> ICalendarComponentType {
+        self.message
+            .components
+            .iter()
+            .find(|comp| comp.component_type != ICalendarComponentType::VCalendar)
+            .map(|comp| comp.component_type.clone())
+            .unwrap_or(ICalendarComponentType::VEvent)
+    }
+
+    fn render_body(&self) -> (String, String, String) {
+        let (subject, narrative) = match &self.summary {
+            ItipSummary::Cancel { instance, summary } => match instance {
+                InstanceId::Recurrence(recurrence_id) => (
+                    format!("Cancelled: {summary} ({recurrence_id})"),
+                    format!(
+                        "{} has cancelled the occurrence on {recurrence_id} of the following event:",
+                        self.from
+                    ),
+                ),
+                InstanceId::Main => (
+                    format!("Cancelled: {summary}"),
+                    format!("{} has cancelled the following event:", self.from),
+                ),
+            },
+            ItipSummary::Rsvp { part_stat, current: _ } => (
+                format!("{}: meeting response", rsvp_verb(part_stat)),
+                format!(
+                    "{} has {} the invitation to:",
+                    self.from,
+                    rsvp_verb(part_stat).to_lowercase()
+                ),
+            ),
+            ItipSummary::Counter(summary) => (
+                format!("New time proposed: {summary}"),
+                format!("{} has proposed new dates for:", self.from),
+            ),
+            ItipSummary::DeclineCounter(summary) => (
+                format!("Proposal declined: {summary}"),
+                format!(
+                    "{} has declined the proposed new dates for:",
+                    self.from
+                ),
+            ),
+            ItipSummary::Rescheduled(summary) => (
+                format!("Rescheduled: {summary}"),
+                format!("{} has rescheduled the following event:", self.from),
+            ),
+            ItipSummary::FreeBusy(_) => (
+                "Free/busy reply".to_string(),
+                format!("{} has reported the following free/busy periods:", self.from),
+            ),
+            ItipSummary::Delegated { current, delegate } => (
+                format!("Delegated: {current}"),
+                format!(
+                    "{} has delegated the following invitation to {delegate}:",
+                    self.from
+                ),
+            ),
+        };
+        let summary_text = self.summary_text();
+        let text_body = format!("{narrative}\n\n{summary_text}");
+        let html_body = format!(
+            "<p>{}</p><p>{}</p>",
+            escape_html(&narrative),
+            escape_html(&summary_text).replace('\n', "<br>")
+        );
+
+        (subject, text_body, html_body)
+    }
+
+    fn summary_text(&self) -> String {
+        match &self.summary {
+            ItipSummary::Cancel { summary, .. } => summary.to_string(),
+            ItipSummary::Rsvp { current, .. } => current.to_string(),
+            ItipSummary::Counter(summary)
+            | ItipSummary::DeclineCounter(summary)
+            | ItipSummary::Rescheduled(summary) => summary.to_string(),
+            ItipSummary::FreeBusy(periods) => periods.clone(),
+            ItipSummary::Delegated { current, .. } => current.to_string(),
+        }
+    }
+}
+
+// The narrative and summary text interpolate calendar fields (ORGANIZER/
+// ATTENDEE CN, delegate address, SUMMARY) that an external party controls,
+// so they must be escaped before landing in the `text/html` MIME part.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn rsvp_verb(part_stat: &ICalendarParticipationStatus) -> &'static str {
+    match part_stat {
+        ICalendarParticipationStatus::Accepted => "Accepted",
+        ICalendarParticipationStatus::Declined => "Declined",
+        ICalendarParticipationStatus::Tentative => "Tentative",
+        ICalendarParticipationStatus::Delegated => "Delegated",
+        _ => "Responded",
+    }
+}